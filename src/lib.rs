@@ -1,111 +1,599 @@
 use std::{
+    collections::HashMap,
     error::Error,
-    io::Write,
-    net::{TcpListener, ToSocketAddrs},
-    sync::{Arc, Mutex},
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, RwLock,
+    },
     thread,
+    time::{Duration, Instant},
 };
 
-use crossbeam_channel::{bounded, Receiver, SendError, Sender, TrySelectError, TrySendError};
+use crossbeam_queue::ArrayQueue;
 
+/// Error returned by [`MJpeg::update_jpeg`] and [`MJpeg::try_update_jpeg`]
+/// when a frame could not be published, carrying the rejected buffer back to
+/// the caller so it isn't lost. This mirrors `std::sync::mpsc::SendError`.
+#[derive(Debug)]
+pub enum MJpegError {
+    /// There are no client threads connected to serve this frame to.
+    Disconnected(Vec<u8>),
+}
+
+impl MJpegError {
+    /// Recover the JPEG buffer that was rejected.
+    pub fn into_inner(self) -> Vec<u8> {
+        match self {
+            MJpegError::Disconnected(buf) => buf,
+        }
+    }
+}
+
+impl fmt::Display for MJpegError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MJpegError::Disconnected(_) => write!(f, "mjpeg: no clients connected"),
+        }
+    }
+}
+
+impl Error for MJpegError {}
+
+/// Number of buffers each [`BufferPool`]'s free list holds onto, inspired by
+/// io_uring's buf_ring model: a small fixed-size group of buffers recycled
+/// between frames instead of reallocating on every frame.
+const DEFAULT_POOL_CAPACITY: usize = 8;
+
+/// A free list of reusable `Vec<u8>` buffers. Buffers are handed out via
+/// [`BufferPool::acquire`] and return themselves automatically (via
+/// [`PooledBuf`]'s `Drop`) once nothing is reading them any more. When the
+/// free list is empty, `acquire` falls back to a fresh allocation rather
+/// than blocking.
+struct BufferPool {
+    free: ArrayQueue<Vec<u8>>,
+}
+
+impl BufferPool {
+    fn new(capacity: usize) -> Self {
+        Self {
+            free: ArrayQueue::new(capacity.max(1)),
+        }
+    }
+
+    fn acquire(self: &Arc<Self>) -> PooledBuf {
+        let mut buf = self.free.pop().unwrap_or_default();
+        buf.clear();
+        PooledBuf::wrap(self.clone(), buf)
+    }
+
+    fn recycle(&self, buf: Vec<u8>) {
+        // If the free list is already full, just drop the buffer: the pool
+        // already holds as many buffers as it keeps.
+        let _ = self.free.push(buf);
+    }
+}
+
+struct PooledBufInner {
+    buf: Vec<u8>,
+    pool: Arc<BufferPool>,
+}
+
+impl Drop for PooledBufInner {
+    fn drop(&mut self) {
+        self.pool.recycle(std::mem::take(&mut self.buf));
+    }
+}
+
+/// A `Vec<u8>` checked out of a [`BufferPool`]. Cloning is cheap (it's an
+/// `Arc` bump, not a byte copy) so every client thread broadcasting the same
+/// frame can hold its own clone; the underlying buffer is returned to the
+/// pool automatically once the last clone is dropped.
+///
+/// Fill a freshly acquired buffer with [`std::io::Write`] (it's empty and
+/// uniquely owned at that point); writing to it after it has been cloned
+/// panics, since at that point other threads may be reading it.
+#[derive(Clone)]
+pub struct PooledBuf {
+    inner: Arc<PooledBufInner>,
+}
+
+impl PooledBuf {
+    fn wrap(pool: Arc<BufferPool>, buf: Vec<u8>) -> Self {
+        Self {
+            inner: Arc::new(PooledBufInner { buf, pool }),
+        }
+    }
+
+    /// Recover the underlying `Vec<u8>` without returning it to the pool.
+    /// Only meant to be called on a buffer that hasn't been shared with any
+    /// client yet (e.g. one rejected by [`MJpeg::update_jpeg_buf`]).
+    fn into_vec(self) -> Vec<u8> {
+        match Arc::try_unwrap(self.inner) {
+            Ok(mut inner) => std::mem::take(&mut inner.buf),
+            Err(shared) => shared.buf.clone(),
+        }
+    }
+}
+
+impl Deref for PooledBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.inner.buf
+    }
+}
+
+impl Write for PooledBuf {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let inner = Arc::get_mut(&mut self.inner)
+            .expect("PooledBuf must not be cloned while it is still being filled");
+        inner.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The pair of buffer pools backing one [`MJpeg`] server: one for frame
+/// bodies, one for the small multipart headers written in front of them.
+/// Cloning just bumps the two inner `Arc`s, so it's cheap to hand a copy to
+/// every [`StreamHandle`].
+#[derive(Clone)]
+struct Pools {
+    header: Arc<BufferPool>,
+    body: Arc<BufferPool>,
+}
+
+impl Pools {
+    fn new(capacity: usize) -> Self {
+        Self {
+            header: Arc::new(BufferPool::new(capacity)),
+            body: Arc::new(BufferPool::new(capacity)),
+        }
+    }
+
+    fn make_frame(&self, body: PooledBuf) -> Frame {
+        let mut header = self.header.acquire();
+        write!(
+            header,
+            "\r\n--MJPEGBOUNDARY\r\nContent-Length: {}\r\nX-Timestamp: 0.000000\r\n\r\n",
+            body.len()
+        )
+        .unwrap();
+        Frame { header, body }
+    }
+}
+
+#[derive(Clone)]
 struct Frame {
-    header: Vec<u8>,
-    body: Vec<u8>,
+    header: PooledBuf,
+    body: PooledBuf,
 }
 
-impl Frame {
-    fn from_jpeg_buf(buf: Vec<u8>) -> Self {
+/// Holds the single most recent frame, plus a sequence number and condvar so
+/// any number of client threads can wait for the next update without
+/// competing with each other for it.
+struct FrameSlot {
+    frame: RwLock<Frame>,
+    seq: Mutex<u64>,
+    updated: Condvar,
+    readers: AtomicUsize,
+}
+
+impl FrameSlot {
+    fn new(pools: &Pools) -> Self {
         Self {
-            header: format!(
-                "\r\n--MJPEGBOUNDARY\r\nContent-Length: {}\r\nX-Timestamp: 0.000000\r\n\r\n",
-                buf.len()
-            )
-            .into_bytes(),
-            body: buf,
+            frame: RwLock::new(pools.make_frame(pools.body.acquire())),
+            seq: Mutex::new(0),
+            updated: Condvar::new(),
+            readers: AtomicUsize::new(0),
         }
     }
+
+    /// Whether any client thread is currently connected and reading this
+    /// stream.
+    fn has_readers(&self) -> bool {
+        self.readers.load(Ordering::SeqCst) > 0
+    }
+
+    fn update(&self, frame: Frame) {
+        *self.frame.write().unwrap() = frame;
+        let mut seq = self.seq.lock().unwrap();
+        *seq += 1;
+        self.updated.notify_all();
+    }
+
+    /// Wait up to `timeout` for a frame newer than `last_seen` to be
+    /// published, then return it together with its sequence number, or
+    /// `None` if `timeout` elapsed first. A caller that was slow and missed
+    /// several updates jumps straight to the newest frame instead of
+    /// replaying stale ones.
+    fn wait_for_update(&self, last_seen: u64, timeout: Duration) -> Option<(u64, Frame)> {
+        let seq = self.seq.lock().unwrap();
+        let (seq, result) = self
+            .updated
+            .wait_timeout_while(seq, timeout, |seq| *seq == last_seen)
+            .unwrap();
+        if result.timed_out() {
+            return None;
+        }
+        let frame = self.frame.read().unwrap().clone();
+        Some((*seq, frame))
+    }
+}
+
+/// Marks one client thread as actively reading `slot` for as long as the
+/// guard is alive, so [`FrameSlot::has_readers`] reflects currently
+/// connected clients; decrements again on drop regardless of how the
+/// client thread exits.
+struct ReaderGuard<'a> {
+    slot: &'a FrameSlot,
+}
+
+impl<'a> ReaderGuard<'a> {
+    fn new(slot: &'a FrameSlot) -> Self {
+        slot.readers.fetch_add(1, Ordering::SeqCst);
+        Self { slot }
+    }
+}
+
+impl Drop for ReaderGuard<'_> {
+    fn drop(&mut self) {
+        self.slot.readers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The path the single-stream convenience API (`update_jpeg`,
+/// `try_update_jpeg`, `is_full`) reads from and writes to.
+const DEFAULT_STREAM_PATH: &str = "/";
+
+/// A parsed HTTP/1.1 request line; we don't need the headers for routing so
+/// they're read and discarded.
+struct Request {
+    method: String,
+    path: String,
+}
+
+impl Request {
+    /// Read and parse the request line and headers off `stream`, stopping at
+    /// the blank line that ends the header block.
+    fn read_from(stream: &TcpStream) -> std::io::Result<Self> {
+        let mut reader = BufReader::new(stream);
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let path = parts.next().unwrap_or_default().to_string();
+
+        loop {
+            let mut header_line = String::new();
+            if reader.read_line(&mut header_line)? == 0
+                || header_line == "\r\n"
+                || header_line == "\n"
+            {
+                break;
+            }
+        }
+
+        Ok(Self { method, path })
+    }
+}
+
+/// How often a client thread wakes up to check whether it should stop, even
+/// if no new frame has arrived.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How long [`ShutdownHandle::stop`] waits for client threads to drain
+/// before giving up and logging the ones still running.
+const SHUTDOWN_JOIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Shared shutdown bookkeeping for an [`MJpeg`] server: the stop flag and
+/// the set of currently running client threads.
+struct ShutdownState {
+    stopped: AtomicBool,
+    threads: Mutex<Vec<thread::JoinHandle<()>>>,
+}
+
+impl ShutdownState {
+    fn new() -> Self {
+        Self {
+            stopped: AtomicBool::new(false),
+            threads: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+/// A cloneable handle that can stop a running [`MJpeg::run`] and wait for its
+/// client threads to drain.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    shutdown: Arc<ShutdownState>,
+}
+
+impl ShutdownHandle {
+    /// Stop the server: flips the shutdown flag, then waits up to
+    /// [`SHUTDOWN_JOIN_TIMEOUT`] for every client thread to drain, logging
+    /// the subject of any thread still alive once the timeout passes.
+    ///
+    /// `stop` can be called at any time, including before [`MJpeg::run`]
+    /// starts listening — `run` polls the stop flag every
+    /// [`SHUTDOWN_POLL_INTERVAL`] via a non-blocking listener, so there's no
+    /// "wake up a blocked accept()" step that could race with `run`'s
+    /// startup.
+    pub fn stop(&self) {
+        self.shutdown.stopped.store(true, Ordering::SeqCst);
+
+        let threads = std::mem::take(&mut *self.shutdown.threads.lock().unwrap());
+        let deadline = Instant::now() + SHUTDOWN_JOIN_TIMEOUT;
+        while Instant::now() < deadline && threads.iter().any(|t| !t.is_finished()) {
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        for handle in threads {
+            if handle.is_finished() {
+                let _ = handle.join();
+            } else {
+                println!(
+                    "client thread {:?} still running after shutdown timeout",
+                    handle.thread().id()
+                );
+            }
+        }
+    }
+}
+
+/// A handle to one named stream registered with [`MJpeg::add_stream`],
+/// pushed to independently of the default stream and any other named
+/// streams.
+pub struct StreamHandle {
+    slot: Arc<FrameSlot>,
+    pools: Pools,
+}
+
+impl StreamHandle {
+    /// See [`MJpeg::acquire_buffer`].
+    pub fn acquire_buffer(&self) -> PooledBuf {
+        self.pools.body.acquire()
+    }
+
+    /// See [`MJpeg::update_jpeg_buf`].
+    pub fn update_jpeg_buf(&self, buf: PooledBuf) -> Result<(), MJpegError> {
+        if !self.slot.has_readers() {
+            return Err(MJpegError::Disconnected(buf.into_vec()));
+        }
+        self.slot.update(self.pools.make_frame(buf));
+        Ok(())
+    }
+
+    /// See [`MJpeg::update_jpeg`].
+    pub fn update_jpeg(&self, buf: Vec<u8>) -> Result<(), MJpegError> {
+        self.update_jpeg_buf(PooledBuf::wrap(self.pools.body.clone(), buf))
+    }
+
+    /// See [`MJpeg::try_update_jpeg`].
+    pub fn try_update_jpeg(&self, buf: Vec<u8>) -> Result<(), MJpegError> {
+        self.update_jpeg(buf)
+    }
+
+    /// See [`MJpeg::is_full`].
+    pub fn is_full(&self) -> bool {
+        false
+    }
 }
 
 pub struct MJpeg {
-    send: Sender<Frame>,
-    recv: Arc<Mutex<Receiver<Frame>>>,
+    streams: Arc<Mutex<HashMap<String, Arc<FrameSlot>>>>,
+    pools: Pools,
+    shutdown: Arc<ShutdownState>,
+}
+
+impl Default for MJpeg {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MJpeg {
     /// 创建一个mjpeg推流器
     /// # example
-    /// ```
+    /// ```no_run
+    /// # use std::sync::Arc;
+    /// # use mjpeg_rs::MJpeg;
     /// let m = Arc::new(MJpeg::new());
     /// ```
     pub fn new() -> Self {
-        let (send, recv) = bounded(1);
-        let recv = Arc::new(Mutex::new(recv));
-        Self { send, recv }
+        Self::with_pool_capacity(DEFAULT_POOL_CAPACITY)
     }
 
-    /// 将流推送到mjpeg
+    /// Like [`MJpeg::new`], but with the given number of buffers (at least
+    /// one) kept on hand for [`MJpeg::acquire_buffer`] (and the equivalent
+    /// internal header pool) to recycle, instead of the default of
+    /// [`DEFAULT_POOL_CAPACITY`]. The pools are shared by the default stream
+    /// and every stream added with [`MJpeg::add_stream`].
+    pub fn with_pool_capacity(capacity: usize) -> Self {
+        let pools = Pools::new(capacity);
+        let mut streams = HashMap::new();
+        streams.insert(
+            DEFAULT_STREAM_PATH.to_string(),
+            Arc::new(FrameSlot::new(&pools)),
+        );
+        Self {
+            streams: Arc::new(Mutex::new(streams)),
+            pools,
+            shutdown: Arc::new(ShutdownState::new()),
+        }
+    }
+
+    /// Register an independent named stream served at `path`, returning a
+    /// [`StreamHandle`] to push frames into it. This turns a single-feed
+    /// server into a small multi-camera one: register `"/front"` and
+    /// `"/rear"` and clients connecting to each path see only that camera's
+    /// frames, while the default single-stream API keeps serving `"/"`.
     /// # example
+    /// ```no_run
+    /// # use std::sync::Arc;
+    /// # use mjpeg_rs::MJpeg;
+    /// # let jpeg_bytes = vec![];
+    /// let m = Arc::new(MJpeg::new());
+    /// let front = m.add_stream("/front");
+    /// front.update_jpeg(jpeg_bytes).unwrap();
     /// ```
+    pub fn add_stream(&self, path: &str) -> StreamHandle {
+        let slot = Arc::new(FrameSlot::new(&self.pools));
+        self.streams
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), slot.clone());
+        StreamHandle {
+            slot,
+            pools: self.pools.clone(),
+        }
+    }
+
+    fn default_slot(&self) -> Arc<FrameSlot> {
+        self.streams
+            .lock()
+            .unwrap()
+            .get(DEFAULT_STREAM_PATH)
+            .expect("default stream is always registered by MJpeg::new")
+            .clone()
+    }
+
+    /// Check out a cleared, reusable buffer to fill with JPEG bytes and pass
+    /// to [`MJpeg::update_jpeg_buf`], avoiding a fresh heap allocation per
+    /// frame once the pool has warmed up.
+    /// # example
+    /// ```no_run
+    /// # use std::{sync::Arc, io::Write};
+    /// # use mjpeg_rs::MJpeg;
+    /// # let jpeg_bytes = vec![];
+    /// let m = Arc::new(MJpeg::new());
+    /// let mut buf = m.acquire_buffer();
+    /// buf.write_all(&jpeg_bytes).unwrap();
+    /// m.update_jpeg_buf(buf).unwrap();
+    /// ```
+    pub fn acquire_buffer(&self) -> PooledBuf {
+        self.pools.body.acquire()
+    }
+
+    /// Publish a buffer acquired via [`MJpeg::acquire_buffer`] as the next
+    /// frame. Prefer this over [`MJpeg::update_jpeg`] on the hot path: the
+    /// buffer comes from the pool, so no fresh `Vec<u8>` is allocated per
+    /// frame once the pool has warmed up.
+    ///
+    /// Returns [`MJpegError::Disconnected`] (handing the buffer back) if no
+    /// client is currently connected to read it.
+    pub fn update_jpeg_buf(&self, buf: PooledBuf) -> Result<(), MJpegError> {
+        let slot = self.default_slot();
+        if !slot.has_readers() {
+            return Err(MJpegError::Disconnected(buf.into_vec()));
+        }
+        slot.update(self.pools.make_frame(buf));
+        Ok(())
+    }
+
+    /// Get a cloneable handle that can stop a running [`MJpeg::run`] and
+    /// wait for its client threads to drain.
+    /// # example
+    /// ```no_run
+    /// # use std::{sync::Arc, thread};
+    /// # use mjpeg_rs::MJpeg;
+    /// let m = Arc::new(MJpeg::new());
+    /// let handle = m.shutdown_handle();
+    /// let mrc = m.clone();
+    /// let server = thread::spawn(move || mrc.run("0.0.0.0:8088").unwrap());
+    /// // ... later, from any thread ...
+    /// handle.stop();
+    /// server.join().unwrap();
+    /// ```
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            shutdown: self.shutdown.clone(),
+        }
+    }
+
+    /// 将流推送到mjpeg
+    ///
+    /// Returns [`MJpegError::Disconnected`] if no client is connected yet —
+    /// a producer that starts publishing before the first viewer connects
+    /// should handle it rather than `unwrap()`, the same way
+    /// [`MJpeg::try_update_jpeg`]'s example does.
+    /// # example
+    /// ```no_run
+    /// # use std::{sync::Arc, thread};
+    /// # use mjpeg_rs::{MJpeg, MJpegError};
+    /// # fn take_one() -> Vec<u8> { vec![] }
     /// let m = Arc::new(MJpeg::new());
     /// let mrc = m.clone();
     /// thread::spawn(move || mrc.run("0.0.0.0:8088").unwrap());
     /// loop {
-    ///     let b = camera.take_one().unwrap();
-    ///     m.update_jpeg(b).unwrap();
+    ///     let b = take_one();
+    ///     match m.update_jpeg(b) {
+    ///         Ok(_) => (),
+    ///         Err(MJpegError::Disconnected(_b)) => println!("no clients connected"),
+    ///     }
     /// }
     /// ```
-    // FIXME: convert this error into our own type (or the one from std),
-    // to avoid exposing our dependency on crossbeam channel.
-    pub fn update_jpeg(&self, buf: Vec<u8>) -> Result<(), SendError<Vec<u8>>> {
-        self.send
-            .send(Frame::from_jpeg_buf(buf))
-            .map_err(|e| SendError(e.0.body))
+    pub fn update_jpeg(&self, buf: Vec<u8>) -> Result<(), MJpegError> {
+        self.update_jpeg_buf(PooledBuf::wrap(self.pools.body.clone(), buf))
     }
 
     /// 将流推送到mjpeg
     /// # example
-    /// ```
+    /// ```no_run
+    /// # use std::{sync::Arc, thread};
+    /// # use mjpeg_rs::{MJpeg, MJpegError};
+    /// # fn take_one() -> Vec<u8> { vec![] }
     /// let m = Arc::new(MJpeg::new());
     /// let mrc = m.clone();
     /// thread::spawn(move || mrc.run("0.0.0.0:8088").unwrap());
     /// loop {
-    ///     let b = camera.take_one().unwrap();
+    ///     let b = take_one();
     ///     match m.try_update_jpeg(b) {
     ///         Ok(_) => (),
-    ///         Err(TrySendError::Full(_b)) => println!("nobody is listening, or queue is backed up")
-    ///         Err(TrySendError::Disconnected(_b)) => {
-    ///             println!("disconnected");
+    ///         Err(MJpegError::Disconnected(_b)) => {
+    ///             println!("no clients connected");
     ///             break;
     ///         }
     ///     }
     /// }
     /// ```
-    // FIXME: convert this error into our own type (or the one from std),
-    // to avoid exposing our dependency on crossbeam channel.
-    pub fn try_update_jpeg(&self, buf: Vec<u8>) -> Result<(), TrySendError<Vec<u8>>> {
-        self.send
-            .try_send(Frame::from_jpeg_buf(buf))
-            .map_err(|e| match e {
-                TrySendError::Disconnected(frame) => TrySendError::Disconnected(frame.body),
-                TrySendError::Full(frame) => TrySendError::Full(frame.body),
-            })
+    pub fn try_update_jpeg(&self, buf: Vec<u8>) -> Result<(), MJpegError> {
+        self.update_jpeg(buf)
     }
 
     /// Ask whether the jpeg queue is full (happens when the reader disconnects or is slow to respond)
+    ///
+    /// Now that every client just reads the latest frame out of a shared
+    /// slot instead of draining a queue, there's nothing that can back up,
+    /// so this always returns `false`.
     pub fn is_full(&self) -> bool {
-        self.send.is_full()
+        false
     }
 
     /// 设置mjpeg服务端口
     /// # example
-    /// ```
+    /// ```no_run
+    /// # use std::{sync::Arc, thread};
+    /// # use mjpeg_rs::{MJpeg, MJpegError};
+    /// # fn take_one() -> Vec<u8> { vec![] }
     /// let m = Arc::new(MJpeg::new());
     /// let mrc = m.clone();
     /// // 此mjpeg-server将运行在8088端口
     /// thread::spawn(move || mrc.run("0.0.0.0:8088").unwrap());
     /// loop {
-    ///     let b = camera.take_one().unwrap();
-    ///     m.update_jpeg(b).unwrap();
+    ///     let b = take_one();
+    ///     match m.update_jpeg(b) {
+    ///         Ok(_) => (),
+    ///         Err(MJpegError::Disconnected(_b)) => println!("no clients connected"),
+    ///     }
     /// }
     /// ```
     pub fn run<A: ToSocketAddrs>(
@@ -113,36 +601,87 @@ impl MJpeg {
         addr: A,
     ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
         let server = TcpListener::bind(addr)?;
-        for stream in server.incoming() {
-            let recv = self.recv.clone();
-            thread::spawn(move || match stream {
-                Ok(stream) => {
-                    let mut stream = stream;
-                    stream.write(b"HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace;boundary=MJPEGBOUNDARY\r\n").unwrap();
-                    stream.flush().unwrap();
-                    loop {
-                        match recv.lock().map(|buf| buf.recv()) {
-                            Ok(frame) => match frame {
-                                Ok(mut frame) => {
-                                    stream.write(&frame.header).unwrap();
-                                    stream.write(&frame.body).unwrap();
-                                    stream.flush().unwrap();
-                                }
-                                Err(e) => {
-                                    println!("recv err{}", e)
-                                }
-                            },
-                            Err(e) => {
-                                println!("lock err{}", e)
-                            }
-                        };
-                    }
+        server.set_nonblocking(true)?;
+
+        while !self.shutdown.stopped.load(Ordering::SeqCst) {
+            let stream = match server.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(SHUTDOWN_POLL_INTERVAL);
+                    continue;
                 }
                 Err(e) => {
-                    println!("stream err{}", e)
+                    println!("stream err{}", e);
+                    continue;
                 }
-            });
+            };
+
+            let streams = self.streams.clone();
+            let shutdown = self.shutdown.clone();
+            let handle = thread::spawn(move || serve_client(stream, &streams, &shutdown));
+
+            // Reap threads that have already finished so a long-running
+            // server doesn't accumulate one `JoinHandle` per client forever.
+            let mut threads = self.shutdown.threads.lock().unwrap();
+            threads.retain(|t| !t.is_finished());
+            threads.push(handle);
         }
         Ok(())
     }
 }
+
+/// Parse the client's request line, route it to the stream registered for
+/// its path, and (for a valid `GET`) stream frames to it until the client
+/// disconnects or the server is shut down. Responds `404` for an unknown
+/// path and `405` for anything other than `GET`.
+fn serve_client(
+    mut stream: TcpStream,
+    streams: &Mutex<HashMap<String, Arc<FrameSlot>>>,
+    shutdown: &ShutdownState,
+) {
+    let request = match Request::read_from(&stream) {
+        Ok(request) => request,
+        Err(e) => {
+            println!("request err{}", e);
+            return;
+        }
+    };
+
+    if request.method != "GET" {
+        let _ = stream.write_all(b"HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\n\r\n");
+        return;
+    }
+
+    let slot = streams.lock().unwrap().get(&request.path).cloned();
+    let slot = match slot {
+        Some(slot) => slot,
+        None => {
+            let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n");
+            return;
+        }
+    };
+
+    if stream
+        .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace;boundary=MJPEGBOUNDARY\r\n")
+        .and_then(|()| stream.flush())
+        .is_err()
+    {
+        return;
+    }
+    let _reader = ReaderGuard::new(&slot);
+    let mut last_seen = 0;
+    while !shutdown.stopped.load(Ordering::SeqCst) {
+        if let Some((seq, frame)) = slot.wait_for_update(last_seen, SHUTDOWN_POLL_INTERVAL) {
+            last_seen = seq;
+            let sent = stream
+                .write_all(&frame.header)
+                .and_then(|()| stream.write_all(&frame.body))
+                .and_then(|()| stream.flush());
+            if sent.is_err() {
+                // The client disconnected mid-stream (e.g. `BrokenPipe`); stop
+                // serving it instead of panicking the thread.
+                break;
+            }
+        }
+    }
+}